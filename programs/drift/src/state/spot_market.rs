@@ -4,11 +4,14 @@ use std::fmt::{Display, Formatter};
 use anchor_lang::prelude::*;
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use crate::error::DriftResult;
+use crate::error::{DriftResult, ErrorCode};
 use crate::instructions::SpotFulfillmentType;
 #[cfg(test)]
 use crate::math::constants::SPOT_CUMULATIVE_INTEREST_PRECISION;
-use crate::math::constants::{AMM_RESERVE_PRECISION, MARGIN_PRECISION, SPOT_WEIGHT_PRECISION_U128};
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, MARGIN_PRECISION, PERCENTAGE_PRECISION_U64,
+    SPOT_WEIGHT_PRECISION_U128,
+};
 use crate::math::margin::{
     calculate_size_discount_asset_weight, calculate_size_premium_liability_weight,
     MarginRequirementType,
@@ -68,7 +71,23 @@ pub struct SpotMarket {
     pub oracle_source: OracleSource,
     pub status: MarketStatus,
     pub asset_tier: AssetTier,
-    pub padding: [u8; 6],
+    // fields below were appended after the initial release; new fields must always be
+    // added here (immediately before `padding`) so deployed accounts keep their byte offsets
+    pub stable_price_model: StablePriceModel,
+    pub optimal_utilization_2: u32, // second kink; 0 collapses the curve onto a single kink
+    pub optimal_borrow_rate_2: u32,
+    // target initial weights for a gradual governance-scheduled migration; 0 duration means no migration is scheduled
+    pub target_initial_asset_weight: u32,
+    pub target_initial_liability_weight: u32,
+    pub weight_migration_start_ts: i64,
+    pub weight_migration_duration: i64,
+    pub deposit_weight_scale_start_quote: u64, // quote value of deposits above which asset weight is scaled down; 0 disables scaling
+    pub net_borrow_limit_per_window_quote: u64, // max net new borrows (in quote) allowed within a rolling window; 0 disables the limit
+    pub net_borrow_window_length_seconds: u64,
+    pub last_net_borrow_window_start_ts: i64,
+    pub net_borrows_in_window: i64,
+    pub loan_origination_fee_rate: u32, // one-time fee on borrow increases, in SPOT_WEIGHT_PRECISION-style bps
+    pub padding: [u8; 2],
 }
 
 impl SpotMarket {
@@ -95,9 +114,21 @@ impl SpotMarket {
         })
     }
 
+    /// Returns a liability-side-analogous asset weight ratio. For
+    /// `MarginRequirementType::Initial` it is already discounted for market-wide
+    /// deposit size via `apply_deposit_weight_scale` (which values total
+    /// exposure at the raw oracle price, per request #4); for
+    /// `MarginRequirementType::Maintenance` it is `maintenance_asset_weight` as is,
+    /// with no deposit scaling or weight migration. Callers computing an actual
+    /// deposit value must separately apply the conservative margin price from
+    /// `get_margin_oracle_price(oracle_price, &SpotBalanceType::Deposit)` (i.e.
+    /// `min(oracle, stable_price)`) to the position's own size, since size/price
+    /// valuation happens outside this module.
     pub fn get_asset_weight(
         &self,
         size: u128,
+        oracle_price: i64,
+        now: i64,
         margin_requirement_type: &MarginRequirementType,
     ) -> DriftResult<u32> {
         let size_precision = 10_u128.pow(self.decimals);
@@ -108,19 +139,59 @@ impl SpotMarket {
             (size * AMM_RESERVE_PRECISION) / size_precision
         };
         let asset_weight = match margin_requirement_type {
-            MarginRequirementType::Initial => calculate_size_discount_asset_weight(
-                size_in_amm_reserve_precision,
-                self.imf_factor,
-                self.initial_asset_weight,
-            )?,
+            MarginRequirementType::Initial => {
+                let initial_asset_weight = self.get_migrated_initial_asset_weight(now)?;
+                let size_discounted_asset_weight = calculate_size_discount_asset_weight(
+                    size_in_amm_reserve_precision,
+                    self.imf_factor,
+                    initial_asset_weight,
+                )?;
+                self.apply_deposit_weight_scale(size_discounted_asset_weight, oracle_price)?
+            }
             MarginRequirementType::Maintenance => self.maintenance_asset_weight,
         };
         Ok(asset_weight)
     }
 
+    /// Scales an asset weight down once the market's total deposits (valued in quote)
+    /// exceed `deposit_weight_scale_start_quote`, so the marginal collateral value this
+    /// market can contribute plateaus rather than growing unbounded with deposits.
+    /// Total exposure is valued at the raw `oracle_price`, not the conservative margin
+    /// price, since understating it here would make this risk cap kick in later than
+    /// intended.
+    fn apply_deposit_weight_scale(&self, asset_weight: u32, oracle_price: i64) -> DriftResult<u32> {
+        if self.deposit_weight_scale_start_quote == 0 {
+            return Ok(asset_weight);
+        }
+
+        let deposit_token_amount =
+            get_token_amount(self.deposit_balance, self, &SpotBalanceType::Deposit)?;
+        let size_precision = 10_u128.pow(self.decimals);
+
+        let total_deposit_quote_value = deposit_token_amount
+            .safe_mul(oracle_price as u128)?
+            .safe_div(size_precision)?;
+
+        if total_deposit_quote_value <= self.deposit_weight_scale_start_quote as u128 {
+            return Ok(asset_weight);
+        }
+
+        let scaled_weight = (asset_weight as u128)
+            .safe_mul(self.deposit_weight_scale_start_quote as u128)?
+            .safe_div(total_deposit_quote_value)?;
+
+        Ok(scaled_weight as u32)
+    }
+
+    /// Returns a price-independent liability weight ratio. Callers computing an actual
+    /// liability value must combine this with the conservative borrow price from
+    /// `get_margin_oracle_price(oracle_price, &SpotBalanceType::Borrow)` (i.e.
+    /// `max(oracle, stable_price)`) themselves, since size/price valuation happens
+    /// outside this module.
     pub fn get_liability_weight(
         &self,
         size: u128,
+        now: i64,
         margin_requirement_type: &MarginRequirementType,
     ) -> DriftResult<u32> {
         let size_precision = 10_u128.pow(self.decimals);
@@ -132,7 +203,7 @@ impl SpotMarket {
         };
 
         let default_liability_weight = match margin_requirement_type {
-            MarginRequirementType::Initial => self.initial_liability_weight,
+            MarginRequirementType::Initial => self.get_migrated_initial_liability_weight(now)?,
             MarginRequirementType::Maintenance => self.maintenance_liability_weight,
         };
 
@@ -148,6 +219,64 @@ impl SpotMarket {
         Ok(liability_weight)
     }
 
+    fn get_migrated_initial_asset_weight(&self, now: i64) -> DriftResult<u32> {
+        self.get_migrated_weight(
+            self.initial_asset_weight,
+            self.target_initial_asset_weight,
+            now,
+        )
+    }
+
+    fn get_migrated_initial_liability_weight(&self, now: i64) -> DriftResult<u32> {
+        self.get_migrated_weight(
+            self.initial_liability_weight,
+            self.target_initial_liability_weight,
+            now,
+        )
+    }
+
+    /// Linearly interpolates `current` towards `target` over
+    /// `weight_migration_duration` seconds starting at `weight_migration_start_ts`.
+    /// Returns `current` unchanged if no migration is scheduled, and clamps to
+    /// `target` once the migration window has elapsed.
+    fn get_migrated_weight(&self, current: u32, target: u32, now: i64) -> DriftResult<u32> {
+        // target == 0 means no migration is scheduled for this leg; start_ts/duration are
+        // shared across both legs, so without this guard scheduling a migration on one
+        // leg (asset or liability) would silently migrate the other leg towards 0 too
+        if target == 0 || self.weight_migration_duration <= 0 || target == current {
+            return Ok(current);
+        }
+
+        let elapsed = now.safe_sub(self.weight_migration_start_ts)?;
+        if elapsed <= 0 {
+            return Ok(current);
+        }
+        if elapsed >= self.weight_migration_duration {
+            return Ok(target);
+        }
+
+        let elapsed = elapsed as u64;
+        let duration = self.weight_migration_duration as u64;
+
+        let migrated_weight = if target >= current {
+            let delta = target.safe_sub(current)?;
+            current.safe_add(
+                (delta as u64)
+                    .safe_mul(elapsed)?
+                    .safe_div(duration)? as u32,
+            )?
+        } else {
+            let delta = current.safe_sub(target)?;
+            current.safe_sub(
+                (delta as u64)
+                    .safe_mul(elapsed)?
+                    .safe_div(duration)? as u32,
+            )?
+        };
+
+        Ok(migrated_weight)
+    }
+
     // get liability weight as if it were perp market margin requirement
     pub fn get_margin_ratio(
         &self,
@@ -173,6 +302,222 @@ impl SpotMarket {
     pub fn get_precision(self) -> u64 {
         10_u64.pow(self.decimals)
     }
+
+    /// Rolls the net borrow window over if it has expired, then checks whether
+    /// `delta_quote` (positive for a borrow increase, negative for a repay) would push
+    /// the rolling net-borrows-in-window above `net_borrow_limit_per_window_quote`.
+    /// A limit of 0 disables the check. The accumulator is floored at 0 so repays can
+    /// only ever bring it back down to "no net borrowing", not into negative territory.
+    pub fn check_net_borrow(&mut self, delta_quote: i64, now: i64) -> DriftResult {
+        // a zero window length would never roll the accumulator over, permanently
+        // ratcheting it towards the limit, so treat the limit as disabled until a
+        // window length is also configured
+        if self.net_borrow_limit_per_window_quote == 0 || self.net_borrow_window_length_seconds == 0
+        {
+            return Ok(());
+        }
+
+        let window_length = self.net_borrow_window_length_seconds as i64;
+        let elapsed = now.safe_sub(self.last_net_borrow_window_start_ts)?;
+        if elapsed >= window_length {
+            self.last_net_borrow_window_start_ts = now;
+            self.net_borrows_in_window = 0;
+        }
+
+        let updated_net_borrows = self.net_borrows_in_window.safe_add(delta_quote)?.max(0);
+
+        if updated_net_borrows > self.net_borrow_limit_per_window_quote as i64 {
+            return Err(ErrorCode::MaxBorrowsExceeded);
+        }
+
+        self.net_borrows_in_window = updated_net_borrows;
+
+        Ok(())
+    }
+
+    /// Returns the one-time fee charged on an increase in borrowed token amount.
+    /// `borrow_delta_token` must already be the increment beyond the prior borrow
+    /// balance (0 for a repay or unchanged balance), so repays never incur a fee and
+    /// partial increases are only charged on the newly-borrowed portion.
+    pub fn calculate_loan_origination_fee(&self, borrow_delta_token: u64) -> DriftResult<u64> {
+        borrow_delta_token
+            .safe_mul(self.loan_origination_fee_rate as u64)?
+            .safe_div(SPOT_WEIGHT_PRECISION_U128 as u64)
+    }
+
+    /// Charges the loan origination fee for a borrow increase of `borrow_delta_token`:
+    /// credits the fee to `revenue_pool` and tallies it in `total_spot_fee`. Returns the
+    /// fee amount, which the caller must add on top of `borrow_delta_token` when
+    /// increasing the user's borrow balance.
+    pub fn apply_loan_origination_fee(&mut self, borrow_delta_token: u64) -> DriftResult<u64> {
+        let fee = self.calculate_loan_origination_fee(borrow_delta_token)?;
+        if fee == 0 {
+            return Ok(0);
+        }
+
+        self.revenue_pool.increase_balance(fee as u128)?;
+        self.total_spot_fee = self.total_spot_fee.safe_add(fee as u128)?;
+
+        Ok(fee)
+    }
+
+    /// Advances the manipulation-resistant stable price towards the live oracle price.
+    ///
+    /// `delay_interval_seconds` controls how quickly the internal lagged EWMA catches up
+    /// to the live oracle price (it is clamped to at least 1 second, so a value of 0
+    /// does NOT disable the model — `stable_price` is always rate-limited by
+    /// `max_growth_per_second` regardless). The model has no effect only until it is
+    /// first initialized; until then `get_stable_price` returns 0 and
+    /// `get_margin_oracle_price` falls back to the raw oracle price.
+    pub fn update_stable_price(&mut self, oracle_price: u64, now: i64) -> DriftResult {
+        let model = &mut self.stable_price_model;
+
+        if !model.initialized {
+            model.delay_price = oracle_price;
+            model.stable_price = oracle_price;
+            model.last_update_ts = now;
+            model.initialized = true;
+            return Ok(());
+        }
+
+        let dt = now.safe_sub(model.last_update_ts)?.max(0) as u64;
+        if dt == 0 {
+            return Ok(());
+        }
+
+        let delay_interval = model.delay_interval_seconds.max(1) as u64;
+        let alpha = dt
+            .min(delay_interval)
+            .safe_mul(PERCENTAGE_PRECISION_U64)?
+            .safe_div(delay_interval)?;
+
+        model.delay_price = if oracle_price >= model.delay_price {
+            model.delay_price.safe_add(
+                oracle_price
+                    .safe_sub(model.delay_price)?
+                    .safe_mul(alpha)?
+                    .safe_div(PERCENTAGE_PRECISION_U64)?,
+            )?
+        } else {
+            model.delay_price.safe_sub(
+                model
+                    .delay_price
+                    .safe_sub(oracle_price)?
+                    .safe_mul(alpha)?
+                    .safe_div(PERCENTAGE_PRECISION_U64)?,
+            )?
+        };
+
+        let max_change = model
+            .stable_price
+            .safe_mul(model.max_growth_per_second as u64)?
+            .safe_div(PERCENTAGE_PRECISION_U64)?
+            .safe_mul(dt)?
+            .max(1);
+
+        model.stable_price = if model.delay_price >= model.stable_price {
+            model
+                .stable_price
+                .safe_add(model.delay_price.safe_sub(model.stable_price)?.min(max_change))?
+        } else {
+            model
+                .stable_price
+                .safe_sub(model.stable_price.safe_sub(model.delay_price)?.min(max_change))?
+        };
+
+        model.last_update_ts = now;
+
+        Ok(())
+    }
+
+    /// Returns the smoothed stable price, or 0 if the model has never been initialized
+    /// (i.e. `update_stable_price` has not yet been called).
+    pub fn get_stable_price(&self) -> u64 {
+        self.stable_price_model.stable_price
+    }
+
+    /// Picks the conservative side of oracle price vs stable price for margin purposes:
+    /// deposits are valued at the lower of the two, borrows at the higher, so a stable
+    /// price model can only ever hurt (not help) a user's margin in a single direction.
+    pub fn get_margin_oracle_price(
+        &self,
+        oracle_price: i64,
+        balance_type: &SpotBalanceType,
+    ) -> DriftResult<i64> {
+        let stable_price = self.get_stable_price();
+        if stable_price == 0 {
+            return Ok(oracle_price);
+        }
+
+        let stable_price = stable_price as i64;
+        Ok(match balance_type {
+            SpotBalanceType::Deposit => oracle_price.min(stable_price),
+            SpotBalanceType::Borrow => oracle_price.max(stable_price),
+        })
+    }
+
+    /// Two-kink piecewise-linear borrow rate curve: `[0, util0] -> [0, rate0]`,
+    /// `[util0, util1] -> [rate0, rate1]`, `[util1, 100%] -> [rate1, max_rate]`.
+    /// `optimal_utilization_2`/`optimal_borrow_rate_2` of 0 collapse the second
+    /// segment onto the first, reproducing the original single-kink curve.
+    pub fn get_borrow_rate(&self, utilization: u64) -> DriftResult<u64> {
+        let optimal_utilization = self.optimal_utilization as u64;
+        let optimal_borrow_rate = self.optimal_borrow_rate as u64;
+        let max_borrow_rate = self.max_borrow_rate as u64;
+
+        let optimal_utilization_2 = if self.optimal_utilization_2 > 0 {
+            self.optimal_utilization_2 as u64
+        } else {
+            optimal_utilization
+        };
+        let optimal_borrow_rate_2 = if self.optimal_borrow_rate_2 > 0 {
+            self.optimal_borrow_rate_2 as u64
+        } else {
+            optimal_borrow_rate
+        };
+
+        let borrow_rate = if utilization <= optimal_utilization {
+            utilization
+                .safe_mul(optimal_borrow_rate)?
+                .safe_div(optimal_utilization.max(1))?
+        } else if utilization <= optimal_utilization_2 {
+            let slope_numerator = optimal_borrow_rate_2.safe_sub(optimal_borrow_rate)?;
+            let slope_denominator = optimal_utilization_2.safe_sub(optimal_utilization)?.max(1);
+            optimal_borrow_rate.safe_add(
+                utilization
+                    .safe_sub(optimal_utilization)?
+                    .safe_mul(slope_numerator)?
+                    .safe_div(slope_denominator)?,
+            )?
+        } else {
+            let slope_numerator = max_borrow_rate.safe_sub(optimal_borrow_rate_2)?;
+            let slope_denominator = PERCENTAGE_PRECISION_U64
+                .safe_sub(optimal_utilization_2)?
+                .max(1);
+            optimal_borrow_rate_2.safe_add(
+                utilization
+                    .min(PERCENTAGE_PRECISION_U64)
+                    .safe_sub(optimal_utilization_2)?
+                    .safe_mul(slope_numerator)?
+                    .safe_div(slope_denominator)?,
+            )?
+        };
+
+        Ok(borrow_rate.min(max_borrow_rate))
+    }
+
+    /// Deposit rate is the borrow rate scaled down by utilization and by the
+    /// share of interest diverted to the insurance fund.
+    pub fn get_deposit_rate(&self, utilization: u64) -> DriftResult<u64> {
+        let borrow_rate = self.get_borrow_rate(utilization)?;
+        let if_factor = self.insurance_fund.total_factor as u64;
+
+        borrow_rate
+            .safe_mul(utilization)?
+            .safe_div(PERCENTAGE_PRECISION_U64)?
+            .safe_mul(PERCENTAGE_PRECISION_U64.safe_sub(if_factor)?)?
+            .safe_div(PERCENTAGE_PRECISION_U64)
+    }
 }
 
 #[cfg(test)]
@@ -292,6 +637,19 @@ impl Default for AssetTier {
     }
 }
 
+#[zero_copy]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct StablePriceModel {
+    pub stable_price: u64,
+    pub last_update_ts: i64,
+    pub delay_price: u64, // internal lagged EWMA used to derive stable_price
+    pub delay_interval_seconds: u32, // clamped to at least 1 second; 0 does not disable the model
+    pub max_growth_per_second: u32, // in PERCENTAGE_PRECISION
+    pub initialized: bool, // true once update_stable_price has run at least once; last_update_ts of 0 is a valid timestamp and cannot be used as the sentinel
+    pub padding: [u8; 7],
+}
+
 #[zero_copy]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -305,4 +663,351 @@ pub struct InsuranceFund {
     pub revenue_settle_period: i64,
     pub total_factor: u32, // percentage of interest for total insurance
     pub user_factor: u32,  // percentage of interest for user staked insurance
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stable_price_is_zero_when_not_initialized() {
+        let market = SpotMarket::default_base_market();
+        assert_eq!(market.get_stable_price(), 0);
+    }
+
+    #[test]
+    fn stable_price_initializes_to_oracle_on_first_update() {
+        let mut market = SpotMarket::default_base_market();
+        market.update_stable_price(100_000_000, 0).unwrap();
+        assert_eq!(market.get_stable_price(), 100_000_000);
+    }
+
+    #[test]
+    fn stable_price_does_not_reinitialize_on_second_update_at_timestamp_zero() {
+        // regression: `now = 0` is a valid timestamp for the very first update, and must
+        // not be re-treated as "uninitialized" on a later update that also happens at dt = 0
+        let mut market = SpotMarket::default_base_market();
+        market.stable_price_model.delay_interval_seconds = 3600;
+        market.stable_price_model.max_growth_per_second = 100;
+
+        market.update_stable_price(100_000_000, 0).unwrap();
+        market.update_stable_price(200_000_000, 0).unwrap();
+
+        // second update happened at the same timestamp (dt = 0) so it must be a no-op,
+        // not a re-initialization that snaps the stable price straight to 200_000_000
+        assert_eq!(market.get_stable_price(), 100_000_000);
+    }
+
+    #[test]
+    fn sudden_oracle_spike_does_not_immediately_inflate_stable_price() {
+        let mut market = SpotMarket::default_base_market();
+        market.stable_price_model.delay_interval_seconds = 3600; // 1 hour
+        market.stable_price_model.max_growth_per_second = 100; // 0.01% per second
+
+        market.update_stable_price(100_000_000, 0).unwrap();
+        assert_eq!(market.get_stable_price(), 100_000_000);
+
+        // oracle doubles one second later
+        market.update_stable_price(200_000_000, 1).unwrap();
+
+        // stable price can move at most max_growth_per_second per elapsed second
+        assert!(market.get_stable_price() < 100_200_000);
+        assert!(market.get_stable_price() > 100_000_000);
+    }
+
+    #[test]
+    fn margin_oracle_price_is_conservative_for_deposits_and_borrows() {
+        let mut market = SpotMarket::default_base_market();
+        market.stable_price_model.delay_interval_seconds = 3600;
+        market.stable_price_model.max_growth_per_second = 100;
+
+        market.update_stable_price(100_000_000, 0).unwrap();
+        market.update_stable_price(200_000_000, 1).unwrap();
+
+        let oracle_price = 200_000_000_i64;
+        let deposit_price = market
+            .get_margin_oracle_price(oracle_price, &SpotBalanceType::Deposit)
+            .unwrap();
+        let borrow_price = market
+            .get_margin_oracle_price(oracle_price, &SpotBalanceType::Borrow)
+            .unwrap();
+
+        // deposits are valued at the lagging stable price, not the spiked oracle price
+        assert!(deposit_price < oracle_price);
+        // borrows are valued at the higher of the two, which is still the live oracle price here
+        assert_eq!(borrow_price, oracle_price);
+    }
+
+    #[test]
+    fn single_kink_borrow_rate_curve_is_unchanged_when_second_kink_unset() {
+        let mut market = SpotMarket::default_base_market();
+        market.optimal_utilization = 800_000; // 80%
+        market.optimal_borrow_rate = 100_000; // 10%
+        market.max_borrow_rate = 1_000_000; // 100%
+
+        assert_eq!(market.get_borrow_rate(0).unwrap(), 0);
+        assert_eq!(market.get_borrow_rate(800_000).unwrap(), 100_000);
+        assert_eq!(market.get_borrow_rate(1_000_000).unwrap(), 1_000_000);
+        assert_eq!(market.get_borrow_rate(900_000).unwrap(), 550_000);
+    }
+
+    #[test]
+    fn two_kink_borrow_rate_curve_pins_at_each_kink() {
+        let mut market = SpotMarket::default_base_market();
+        market.optimal_utilization = 600_000; // 60%
+        market.optimal_borrow_rate = 50_000; // 5%
+        market.optimal_utilization_2 = 800_000; // 80%
+        market.optimal_borrow_rate_2 = 150_000; // 15%
+        market.max_borrow_rate = 1_000_000; // 100%
+
+        assert_eq!(market.get_borrow_rate(0).unwrap(), 0);
+        assert_eq!(market.get_borrow_rate(600_000).unwrap(), 50_000);
+        assert_eq!(market.get_borrow_rate(700_000).unwrap(), 100_000);
+        assert_eq!(market.get_borrow_rate(800_000).unwrap(), 150_000);
+        assert_eq!(market.get_borrow_rate(900_000).unwrap(), 575_000);
+        assert_eq!(market.get_borrow_rate(1_000_000).unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn weight_migration_pre_start_uses_current_weight() {
+        let mut market = SpotMarket::default_base_market();
+        market.target_initial_asset_weight = 5000;
+        market.weight_migration_start_ts = 100;
+        market.weight_migration_duration = 1000;
+
+        let weight = market
+            .get_asset_weight(0, 100_000_000, 50, &MarginRequirementType::Initial)
+            .unwrap();
+        assert_eq!(weight, market.initial_asset_weight);
+    }
+
+    #[test]
+    fn weight_migration_interpolates_mid_window() {
+        let mut market = SpotMarket::default_base_market();
+        market.initial_asset_weight = 8000;
+        market.target_initial_asset_weight = 5000;
+        market.weight_migration_start_ts = 0;
+        market.weight_migration_duration = 1000;
+
+        let weight = market
+            .get_asset_weight(0, 100_000_000, 500, &MarginRequirementType::Initial)
+            .unwrap();
+        // halfway through an 8000 -> 5000 migration
+        assert_eq!(weight, 6500);
+    }
+
+    #[test]
+    fn weight_migration_clamps_to_target_after_completion() {
+        let mut market = SpotMarket::default_base_market();
+        market.initial_liability_weight = 12000;
+        market.target_initial_liability_weight = 15000;
+        market.weight_migration_start_ts = 0;
+        market.weight_migration_duration = 1000;
+
+        let weight = market
+            .get_liability_weight(0, 2000, &MarginRequirementType::Initial)
+            .unwrap();
+        assert_eq!(weight, 15000);
+    }
+
+    #[test]
+    fn weight_migration_on_asset_leg_only_leaves_liability_weight_unchanged() {
+        let mut market = SpotMarket::default_base_market();
+        market.target_initial_asset_weight = 5000;
+        market.weight_migration_start_ts = 0;
+        market.weight_migration_duration = 1000;
+        // target_initial_liability_weight left at its default of 0
+
+        let liability_weight = market
+            .get_liability_weight(0, 500, &MarginRequirementType::Initial)
+            .unwrap();
+        assert_eq!(liability_weight, market.initial_liability_weight);
+    }
+
+    #[test]
+    fn weight_migration_on_liability_leg_only_leaves_asset_weight_unchanged() {
+        let mut market = SpotMarket::default_base_market();
+        market.target_initial_liability_weight = 15000;
+        market.weight_migration_start_ts = 0;
+        market.weight_migration_duration = 1000;
+        // target_initial_asset_weight left at its default of 0
+
+        let asset_weight = market
+            .get_asset_weight(0, 100_000_000, 500, &MarginRequirementType::Initial)
+            .unwrap();
+        assert_eq!(asset_weight, market.initial_asset_weight);
+    }
+
+    #[test]
+    fn deposit_weight_scale_disabled_when_zero() {
+        let mut market = SpotMarket::default_base_market();
+        market.deposit_balance = 10_000_000_000;
+
+        let weight = market
+            .get_asset_weight(0, 1_000_000, 0, &MarginRequirementType::Initial)
+            .unwrap();
+        assert_eq!(weight, market.initial_asset_weight);
+    }
+
+    #[test]
+    fn deposit_weight_scale_progressively_reduces_weight_as_deposits_grow() {
+        let mut market = SpotMarket::default_base_market();
+        market.deposit_weight_scale_start_quote = 1; // scaling kicks in once deposits are worth more than 1 quote unit
+        market.deposit_balance = 1_000_000;
+
+        let weight_small = market
+            .get_asset_weight(0, 1_000_000, 0, &MarginRequirementType::Initial)
+            .unwrap();
+
+        market.deposit_balance = 10_000_000;
+        let weight_large = market
+            .get_asset_weight(0, 1_000_000, 0, &MarginRequirementType::Initial)
+            .unwrap();
+
+        assert!(weight_large < weight_small);
+    }
+
+    #[test]
+    fn net_borrow_limit_disabled_by_default() {
+        let mut market = SpotMarket::default_base_market();
+        market.check_net_borrow(1_000_000_000, 0).unwrap();
+        assert_eq!(market.net_borrows_in_window, 0);
+    }
+
+    #[test]
+    fn net_borrow_limit_disabled_when_window_length_is_zero() {
+        // regression: a limit configured without a window length must not ratchet
+        // the accumulator forever since it would never roll over
+        let mut market = SpotMarket::default_base_market();
+        market.net_borrow_limit_per_window_quote = 1_000;
+
+        market.check_net_borrow(10_000, 0).unwrap();
+        assert_eq!(market.net_borrows_in_window, 0);
+
+        market.check_net_borrow(10_000, 100).unwrap();
+        assert_eq!(market.net_borrows_in_window, 0);
+    }
+
+    #[test]
+    fn net_borrow_limit_rejects_borrow_over_the_cap() {
+        let mut market = SpotMarket::default_base_market();
+        market.net_borrow_limit_per_window_quote = 1_000;
+        market.net_borrow_window_length_seconds = 3600;
+
+        market.check_net_borrow(600, 0).unwrap();
+        assert_eq!(market.net_borrows_in_window, 600);
+
+        let result = market.check_net_borrow(500, 10);
+        assert!(result.is_err());
+        // the rejected borrow must not have been applied
+        assert_eq!(market.net_borrows_in_window, 600);
+    }
+
+    #[test]
+    fn net_borrow_limit_repay_floors_at_zero() {
+        let mut market = SpotMarket::default_base_market();
+        market.net_borrow_limit_per_window_quote = 1_000;
+        market.net_borrow_window_length_seconds = 3600;
+
+        market.check_net_borrow(200, 0).unwrap();
+        market.check_net_borrow(-500, 10).unwrap();
+
+        assert_eq!(market.net_borrows_in_window, 0);
+    }
+
+    #[test]
+    fn net_borrow_limit_window_rolls_over() {
+        let mut market = SpotMarket::default_base_market();
+        market.net_borrow_limit_per_window_quote = 1_000;
+        market.net_borrow_window_length_seconds = 3600;
+
+        market.check_net_borrow(900, 0).unwrap();
+        assert_eq!(market.net_borrows_in_window, 900);
+
+        // past the window length, the accumulator resets before the new borrow applies
+        market.check_net_borrow(900, 3601).unwrap();
+        assert_eq!(market.net_borrows_in_window, 900);
+        assert_eq!(market.last_net_borrow_window_start_ts, 3601);
+    }
+
+    #[test]
+    fn loan_origination_fee_on_first_borrow() {
+        let mut market = SpotMarket::default_base_market();
+        market.loan_origination_fee_rate = 10; // 0.1%
+
+        let prior_borrow = 0_u64;
+        let new_borrow = 1_000_000_u64;
+        let fee = market
+            .calculate_loan_origination_fee(new_borrow.saturating_sub(prior_borrow))
+            .unwrap();
+
+        assert_eq!(fee, 1_000);
+    }
+
+    #[test]
+    fn loan_origination_fee_only_charged_on_incremental_borrow() {
+        let mut market = SpotMarket::default_base_market();
+        market.loan_origination_fee_rate = 10;
+
+        let prior_borrow = 1_000_000_u64;
+        let new_borrow = 1_500_000_u64;
+        let fee = market
+            .calculate_loan_origination_fee(new_borrow.saturating_sub(prior_borrow))
+            .unwrap();
+
+        // fee is only charged on the 500_000 increment, not the full new balance
+        assert_eq!(fee, 500);
+    }
+
+    #[test]
+    fn loan_origination_fee_is_zero_on_repay() {
+        let mut market = SpotMarket::default_base_market();
+        market.loan_origination_fee_rate = 10;
+
+        let prior_borrow = 1_000_000_u64;
+        let new_borrow = 400_000_u64;
+        let fee = market
+            .calculate_loan_origination_fee(new_borrow.saturating_sub(prior_borrow))
+            .unwrap();
+
+        assert_eq!(fee, 0);
+    }
+
+    #[test]
+    fn loan_origination_fee_on_reborrow_after_full_repay() {
+        let mut market = SpotMarket::default_base_market();
+        market.loan_origination_fee_rate = 10;
+
+        let prior_borrow = 0_u64; // fully repaid before reborrowing
+        let new_borrow = 2_000_000_u64;
+        let fee = market
+            .calculate_loan_origination_fee(new_borrow.saturating_sub(prior_borrow))
+            .unwrap();
+
+        assert_eq!(fee, 2_000);
+    }
+
+    #[test]
+    fn loan_origination_fee_credits_revenue_pool_and_total_spot_fee() {
+        let mut market = SpotMarket::default_base_market();
+        market.loan_origination_fee_rate = 10;
+
+        let fee = market.apply_loan_origination_fee(1_000_000).unwrap();
+
+        assert_eq!(fee, 1_000);
+        assert_eq!(market.total_spot_fee, 1_000);
+        assert_eq!(market.revenue_pool.balance(), 1_000);
+    }
+
+    #[test]
+    fn loan_origination_fee_on_repay_credits_nothing() {
+        let mut market = SpotMarket::default_base_market();
+        market.loan_origination_fee_rate = 10;
+
+        let fee = market.apply_loan_origination_fee(0).unwrap();
+
+        assert_eq!(fee, 0);
+        assert_eq!(market.total_spot_fee, 0);
+        assert_eq!(market.revenue_pool.balance(), 0);
+    }
 }
\ No newline at end of file